@@ -24,47 +24,107 @@ crate-type = ["cdylib"]
 fn build_stub() -> Result<()> {
     let symbols = std::fs::read_to_string("src/symbols.txt")?;
     let outdir = env::var("OUT_DIR")?;
+    let target = env::var("TARGET")?;
+
     let project_path = PathBuf::from(&outdir).join("libbinder_ndk");
-    if project_path.exists() {
-        std::fs::remove_dir_all(&project_path)?;
-    }
-    std::fs::create_dir(&project_path)?;
-
-    let project_cargo_path = project_path.join("Cargo.toml");
-    std::fs::File::create(&project_cargo_path)?;
-    std::fs::write(&project_cargo_path, CARGO_CONTENT)?;
-    let src_path = project_path.join("src");
-    std::fs::create_dir_all(&src_path)?;
-    let mut f = std::fs::File::create(src_path.join("lib.rs"))?;
-    for symbol in symbols.split("\n") {
-        if !symbol.is_empty() {
-            f.write_all(format!("#[no_mangle]\npub extern \"C\" fn {}() {{}}\n", symbol).as_bytes())?;
-        }
+    let build_dir = format!("{}/{}/{}", outdir, target, "debug");
+
+    // 将符号列表去重并排序后生成单一源文件，使得 symbols.txt 中的重排或重复项
+    // 不会改变产物，从而保持缓存有效
+    let mut unique: Vec<&str> = symbols
+        .split('\n')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    unique.sort_unstable();
+    unique.dedup();
+
+    let mut lib_rs = String::new();
+    for symbol in &unique {
+        lib_rs.push_str(&format!(
+            "#[no_mangle]\npub extern \"C\" fn {}() {{}}\n",
+            symbol
+        ));
     }
-    f.flush()?;
 
-    let target = env::var("TARGET")?;
-    Command::new("cargo")
-        .arg("build")
-        .arg("--target")
-        .arg(&target)
-        .arg("--manifest-path")
-        .arg(project_cargo_path)
-        .arg("--target-dir")
-        .arg(&outdir)
-        .current_dir(&project_path)
-        .status()?;
+    // 将规范化后的源文件内容与当前 TARGET 一起哈希成戳记，仅在戳记变化或产物缺失时才重建
+    let stamp = stub_stamp(&lib_rs, &target);
+    let stamp_path = PathBuf::from(&outdir).join("libbinder_ndk.stamp");
+    let stamp_matches = std::fs::read_to_string(&stamp_path)
+        .map(|s| s.trim() == stamp)
+        .unwrap_or(false);
+
+    if !(stamp_matches && stub_output_exists(&build_dir)) {
+        if project_path.exists() {
+            std::fs::remove_dir_all(&project_path)?;
+        }
+        std::fs::create_dir(&project_path)?;
+
+        let project_cargo_path = project_path.join("Cargo.toml");
+        std::fs::write(&project_cargo_path, CARGO_CONTENT)?;
+        let src_path = project_path.join("src");
+        std::fs::create_dir_all(&src_path)?;
+        let mut f = std::fs::File::create(src_path.join("lib.rs"))?;
+        f.write_all(lib_rs.as_bytes())?;
+        f.flush()?;
+
+        Command::new("cargo")
+            .arg("build")
+            .arg("--target")
+            .arg(&target)
+            .arg("--manifest-path")
+            .arg(project_cargo_path)
+            .arg("--target-dir")
+            .arg(&outdir)
+            .current_dir(&project_path)
+            .status()?;
+
+        std::fs::write(&stamp_path, &stamp)?;
+    } else {
+        println!("cargo:warning=stub 未变化，跳过重建: {}", stamp);
+    }
 
     // we always use debug build for stub due to speed!
-    println!(
-        "cargo:rustc-link-search={}",
-        format!("{}/{}/{}", outdir, target, "debug")
-    );
+    println!("cargo:rustc-link-search={}", build_dir);
     println!("cargo:rustc-link-lib=binder_ndk");
 
     Ok(())
 }
 
+/// 将规范化后的 stub 源文件内容与 TARGET 哈希成戳记字符串
+fn stub_stamp(lib_rs: &str, target: &str) -> String {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    lib_rs.hash(&mut hasher);
+    target.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// 判断 stub 的产物（cdylib 的 `.so`/`.a` 等）是否已存在于构建目录
+fn stub_output_exists(build_dir: &str) -> bool {
+    let entries = match std::fs::read_dir(build_dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.contains("binder_ndk")
+            && (name.ends_with(".so")
+                || name.ends_with(".a")
+                || name.ends_with(".dll")
+                || name.ends_with(".dylib"))
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
 fn main() {
     println!("cargo:rerun-if-changed=src/BinderBindings.hpp");
     println!("cargo:rerun-if-changed=src/wrapper.h");
@@ -79,14 +139,18 @@ fn main() {
         }
     }
 
+    // 根据 TARGET 推导 clang 三元组，并从环境变量读取 API 级别，避免硬编码 aarch64/API 33
+    let clang_triple = clang_target_triple();
+    let api_level = android_api_level();
+
     // 构建 bindgen builder
     let mut builder = bindgen::Builder::default()
         .clang_arg("-Isrc/include_cpp")
         .clang_arg("-Isrc/include_ndk")
         .clang_arg("-Isrc/include_platform")
         .clang_arg("-target")
-        .clang_arg("aarch64-linux-android")
-        .clang_arg("-D__ANDROID_API__=33")
+        .clang_arg(clang_triple.clone())
+        .clang_arg(format!("-D__ANDROID_API__={}", api_level))
         .clang_arg("-D__ANDROID__")
         .default_enum_style(EnumVariation::Rust {
             non_exhaustive: true,
@@ -146,7 +210,7 @@ fn main() {
         .clang_arg("-D__STDC_CONSTANT_MACROS")
         .clang_arg("-D__STDC_FORMAT_MACROS")
         .clang_arg("-target")
-        .clang_arg("aarch64-linux-android33")
+        .clang_arg(format!("{}{}", clang_triple, api_level))
         .clang_arg("-fno-addrsig")
         .clang_arg("-include")
         .clang_arg("src/types_workaround.h")
@@ -170,12 +234,17 @@ fn setup_ndk_include_paths() -> Option<Vec<PathBuf>> {
     println!("cargo:warning=Found Android NDK at: {}", ndk_home.display());
     
     let mut paths = Vec::new();
-    
+
     // 对于 NDK r28b+，sysroot 在 toolchains/llvm/prebuilt/host-tag/sysroot
     let host_tag = get_host_tag();
     let sysroot_base = ndk_home.join(format!("toolchains/llvm/prebuilt/{}/sysroot", host_tag));
-    
-    if sysroot_base.exists() {
+
+    // 依据解析出的主版本号确定布局：r19+ 使用统一工具链的 sysroot，旧版使用顶层 sysroot
+    let use_unified_layout = ndk_version(&ndk_home)
+        .map(|(major, _)| major >= MIN_NDK_MAJOR)
+        .unwrap_or_else(|| sysroot_base.exists());
+
+    if use_unified_layout {
         // 基础系统头文件
         paths.push(sysroot_base.join("usr/include"));
         
@@ -247,8 +316,18 @@ fn setup_ndk_include_paths() -> Option<Vec<PathBuf>> {
 }
 
 /// 检测 Android NDK 安装路径
+///
+/// 查找顺序：显式元数据、环境变量、SDK 的 `ndk/<version>`（现代并列布局）、
+/// 已弃用的 `ndk-bundle`，最后才是硬编码的常见位置。
 fn detect_android_ndk() -> Option<PathBuf> {
-    // 1. 从环境变量获取
+    // 1. 包元数据中配置的 NDK 根目录（最高优先级，声明式且可复现）
+    if let Some(path) = ndk_from_metadata() {
+        if path.exists() && is_valid_ndk(&path) {
+            return Some(path);
+        }
+    }
+
+    // 2. 从环境变量获取
     let env_vars = ["ANDROID_NDK_HOME", "NDK_ROOT", "ANDROID_NDK_ROOT"];
     for var in &env_vars {
         if let Ok(path_str) = env::var(var) {
@@ -258,33 +337,106 @@ fn detect_android_ndk() -> Option<PathBuf> {
             }
         }
     }
-    
-    // 2. 检查常见安装位置
+
+    // 3. 检查 Android SDK 中的 NDK
+    if let Ok(sdk_root) = env::var("ANDROID_SDK_ROOT") {
+        let sdk_root = PathBuf::from(sdk_root);
+
+        // 3a. 现代并列布局 ndk/<version>，选择最高的有效修订号
+        if let Some(path) = highest_ndk_in_sdk(&sdk_root) {
+            return Some(path);
+        }
+
+        // 3b. 已弃用的 ndk-bundle
+        let ndk_bundle = sdk_root.join("ndk-bundle");
+        if ndk_bundle.exists() && is_valid_ndk(&ndk_bundle) {
+            return Some(ndk_bundle);
+        }
+    }
+
+    // 4. 检查常见安装位置
     let common_paths = [
         "C:/Android/android-ndk-r28b",
-        "D:/android-ndk-r28b", 
+        "D:/android-ndk-r28b",
         "C:/android-ndk-r28b",
         "D:/Android/android-ndk-r28b",
     ];
-    
+
     for path_str in &common_paths {
         let path = PathBuf::from(path_str);
         if path.exists() && is_valid_ndk(&path) {
             return Some(path);
         }
     }
-    
-    // 3. 检查 Android SDK 中的 NDK
-    if let Ok(sdk_root) = env::var("ANDROID_SDK_ROOT") {
-        let ndk_bundle = PathBuf::from(sdk_root).join("ndk-bundle");
-        if ndk_bundle.exists() && is_valid_ndk(&ndk_bundle) {
-            return Some(ndk_bundle);
+
+    None
+}
+
+/// 从包元数据读取配置的 NDK 根目录
+///
+/// 解析 `CARGO_MANIFEST_DIR` 下 `Cargo.toml` 中的
+/// `[package.metadata.binder_ndk] ndk = "..."`。
+///
+/// 注意：Cargo 不会把 `package.metadata.*` 透传给依赖的构建脚本，因此这里读取的是
+/// **本 crate（`binder_ndk_sys`）自身** 的清单。在工作空间中直接固定
+/// `binder_ndk_sys` 时可用；消费 crate 若要声明式地指定 NDK 位置，应通过
+/// `ANDROID_NDK_HOME` 等环境变量或在本 crate 的清单中配置。
+fn ndk_from_metadata() -> Option<PathBuf> {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").ok()?;
+    let content = std::fs::read_to_string(PathBuf::from(manifest_dir).join("Cargo.toml")).ok()?;
+    ndk_from_manifest(&content)
+}
+
+/// 从清单内容中解析 `[package.metadata.binder_ndk] ndk = "..."`
+fn ndk_from_manifest(content: &str) -> Option<PathBuf> {
+    let mut in_section = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_section = line == "[package.metadata.binder_ndk]";
+            continue;
+        }
+        if in_section {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "ndk" {
+                    let value = value.trim().trim_matches('"');
+                    if !value.is_empty() {
+                        return Some(PathBuf::from(value));
+                    }
+                }
+            }
         }
     }
-    
+
     None
 }
 
+/// 在 SDK 的 `ndk/` 目录中选择修订号最高的有效 NDK（并列布局）
+fn highest_ndk_in_sdk(sdk_root: &PathBuf) -> Option<PathBuf> {
+    let ndk_dir = sdk_root.join("ndk");
+    let entries = std::fs::read_dir(&ndk_dir).ok()?;
+
+    let mut candidates = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() || !is_valid_ndk(&path) {
+            continue;
+        }
+        let version = ndk_version(&path).unwrap_or((0, 0));
+        candidates.push((version, path));
+    }
+
+    pick_highest_ndk(candidates)
+}
+
+/// 从候选集合中选择修订号最高的 NDK
+fn pick_highest_ndk(candidates: Vec<((u32, u32), PathBuf)>) -> Option<PathBuf> {
+    candidates
+        .into_iter()
+        .max_by_key(|(version, _)| *version)
+        .map(|(_, path)| path)
+}
+
 /// 自动设置工具链符号链接以解决版本号问题
 fn setup_toolchain_links(ndk_path: &PathBuf) -> Result<()> {
     let host_tag = get_host_tag();
@@ -361,11 +513,8 @@ fn setup_target_toolchain_links(bin_dir: &PathBuf, target: &str) -> Result<()> {
         
         if !target_path.exists() {
             println!("cargo:warning=创建工具链链接: {} -> {}", target_name, latest_clang);
-            
-            // 尝试创建符号链接，失败则复制文件
-            if std::os::windows::fs::symlink_file(&source_path, &target_path).is_err() {
-                std::fs::copy(&source_path, &target_path)?;
-            }
+
+            link_or_copy(&source_path, &target_path)?;
         } else {
             println!("cargo:warning=工具链链接已存在: {}", target_name);
         }
@@ -406,11 +555,8 @@ fn setup_ar_links(bin_dir: &PathBuf, targets: &[&str]) -> Result<()> {
             
             if !target_ar_path.exists() {
                 println!("cargo:warning=创建 ar 链接: {} -> {}", target_ar_name, ar_name);
-                
-                // 尝试创建符号链接，失败则复制文件
-                if std::os::windows::fs::symlink_file(&source_path, &target_ar_path).is_err() {
-                    std::fs::copy(&source_path, &target_ar_path)?;
-                }
+
+                link_or_copy(&source_path, &target_ar_path)?;
             } else {
                 println!("cargo:warning=ar 链接已存在: {}", target_ar_name);
             }
@@ -422,16 +568,80 @@ fn setup_ar_links(bin_dir: &PathBuf, targets: &[&str]) -> Result<()> {
     Ok(())
 }
 
+/// 支持的最低 NDK 主版本号（r19，首个统一工具链布局）
+const MIN_NDK_MAJOR: u32 = 19;
+
+/// 从 `<ndk>/source.properties` 解析 NDK 修订号
+///
+/// 读取 `Pkg.Revision = <major>.<minor>.<build>` 行并返回 `(major, minor)`，
+/// 取代以往依赖固定目录名（如 `android-ndk-r28b`）来判断版本的做法。
+fn ndk_version(path: &PathBuf) -> Option<(u32, u32)> {
+    let content = std::fs::read_to_string(path.join("source.properties")).ok()?;
+    parse_pkg_revision(&content)
+}
+
+/// 从 `source.properties` 内容解析 `Pkg.Revision = <major>.<minor>.<build>`
+fn parse_pkg_revision(content: &str) -> Option<(u32, u32)> {
+    for line in content.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        if key == "Pkg.Revision" {
+            let mut parts = value.split('.');
+            let major = parts.next()?.parse::<u32>().ok()?;
+            let minor = parts.next().and_then(|m| m.parse::<u32>().ok()).unwrap_or(0);
+            return Some((major, minor));
+        }
+    }
+    None
+}
+
 /// 验证是否为有效的 NDK 安装
+///
+/// 通过 `source.properties` 强制要求至少为 r19（首个统一工具链布局）；低于此
+/// 下限的安装会发出带有检测到的修订号的 `cargo:warning`，以便给出可操作的错误。
 fn is_valid_ndk(path: &PathBuf) -> bool {
-    // 检查新版 NDK 结构 (r28b+)
-    let new_sysroot = path.join("toolchains/llvm/prebuilt").join(get_host_tag()).join("sysroot");
-    let has_new_structure = new_sysroot.exists() && path.join("toolchains/llvm/prebuilt").exists();
-    
-    // 检查旧版 NDK 结构
-    let has_old_structure = path.join("sysroot").exists() && path.join("toolchains/llvm/prebuilt").exists();
-    
-    has_new_structure || has_old_structure
+    if !path.join("toolchains/llvm/prebuilt").exists() {
+        return false;
+    }
+
+    match ndk_version(path) {
+        Some((major, _)) if major >= MIN_NDK_MAJOR => true,
+        Some((major, minor)) => {
+            println!(
+                "cargo:warning=检测到的 NDK 修订号 r{}.{} 过低，至少需要 r{}",
+                major, minor, MIN_NDK_MAJOR
+            );
+            false
+        }
+        None => {
+            // 无法解析版本号时退回到目录结构检查，保持对异常安装的兼容
+            let new_sysroot = path
+                .join("toolchains/llvm/prebuilt")
+                .join(get_host_tag())
+                .join("sysroot");
+            new_sysroot.exists() || path.join("sysroot").exists()
+        }
+    }
+}
+
+/// 跨平台创建工具链链接：优先使用符号链接，失败时回退到复制文件
+///
+/// Windows 主机使用 `std::os::windows::fs::symlink_file`，Unix 主机（Linux/macOS）
+/// 使用 `std::os::unix::fs::symlink`。任一平台在符号链接创建失败（例如缺少权限）时
+/// 都会回退到 `std::fs::copy`，保证在 NDK 所支持的三种主机平台上都能工作。
+fn link_or_copy(source: &PathBuf, target: &PathBuf) -> Result<()> {
+    #[cfg(windows)]
+    let link_result = std::os::windows::fs::symlink_file(source, target);
+    #[cfg(unix)]
+    let link_result = std::os::unix::fs::symlink(source, target);
+
+    if link_result.is_err() {
+        std::fs::copy(source, target)?;
+    }
+
+    Ok(())
 }
 
 /// 获取主机平台标识
@@ -447,6 +657,42 @@ fn get_host_tag() -> &'static str {
     }
 }
 
+/// 根据 Cargo 的 `TARGET` 推导 Android clang 目标三元组
+///
+/// 与 `setup_ndk_include_paths` 中的架构映射保持一致，确保生成的 `bindings.rs`
+/// 在 32 位 ARM、x86 与 x86_64 上拥有正确的指针宽度与类型布局。
+fn clang_target_triple() -> String {
+    let target = env::var("TARGET").unwrap_or_default();
+    let triple = match target.as_str() {
+        t if t.contains("aarch64") => "aarch64-linux-android",
+        t if t.contains("armv7") => "armv7a-linux-androideabi",
+        t if t.contains("i686") => "i686-linux-android",
+        t if t.contains("x86_64") => "x86_64-linux-android",
+        _ => "aarch64-linux-android", // 默认
+    };
+    triple.to_string()
+}
+
+/// 解析 Android API 级别，优先读取 `ANDROID_API_LEVEL`/`ANDROID_PLATFORM`
+///
+/// `ANDROID_PLATFORM` 允许 `android-33` 或 `33` 两种写法；当两个变量都未设置时
+/// 退回到一个合理的最小值（21，对应 unified toolchain 的常见下限）。
+fn android_api_level() -> u32 {
+    const DEFAULT_API_LEVEL: u32 = 21;
+
+    let raw = env::var("ANDROID_API_LEVEL")
+        .or_else(|_| env::var("ANDROID_PLATFORM"))
+        .ok();
+
+    raw.and_then(|v| {
+        v.trim()
+            .trim_start_matches("android-")
+            .parse::<u32>()
+            .ok()
+    })
+    .unwrap_or(DEFAULT_API_LEVEL)
+}
+
 /// 收集额外的 clang 参数
 fn collect_extra_clang_args() -> Vec<String> {
     let mut args = Vec::new();
@@ -491,3 +737,49 @@ fn normalize_path_for_clang(path: &PathBuf) -> String {
         path_str.into_owned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_real_source_properties_format() {
+        let content = "Pkg.Desc = Android NDK\nPkg.Revision = 28.0.12674087\n";
+        assert_eq!(parse_pkg_revision(content), Some((28, 0)));
+    }
+
+    #[test]
+    fn missing_minor_defaults_to_zero() {
+        assert_eq!(parse_pkg_revision("Pkg.Revision = 19\n"), Some((19, 0)));
+    }
+
+    #[test]
+    fn no_revision_line_yields_none() {
+        assert_eq!(parse_pkg_revision("Pkg.Desc = Android NDK\n"), None);
+    }
+
+    #[test]
+    fn picks_highest_revision_among_candidates() {
+        let candidates = vec![
+            ((25, 1), PathBuf::from("/sdk/ndk/25.1")),
+            ((27, 0), PathBuf::from("/sdk/ndk/27.0")),
+            ((26, 3), PathBuf::from("/sdk/ndk/26.3")),
+        ];
+        assert_eq!(
+            pick_highest_ndk(candidates),
+            Some(PathBuf::from("/sdk/ndk/27.0"))
+        );
+    }
+
+    #[test]
+    fn metadata_matches_exact_key_only() {
+        let manifest = "[package.metadata.binder_ndk]\nndk_version = \"bogus\"\nndk = \"/opt/ndk\"\n";
+        assert_eq!(ndk_from_manifest(manifest), Some(PathBuf::from("/opt/ndk")));
+    }
+
+    #[test]
+    fn metadata_ignores_other_sections() {
+        let manifest = "[package]\nndk = \"/wrong\"\n";
+        assert_eq!(ndk_from_manifest(manifest), None);
+    }
+}